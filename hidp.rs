@@ -1,7 +1,6 @@
-/// Bluetooth HID protocol (HIDP) as defined in section 3 of the Bluetooth HID specification. 
-use std::io::{self, Read};
+/// Bluetooth HID protocol (HIDP) as defined in section 3 of the Bluetooth HID specification.
+use std::fmt;
 use std::iter;
-use std::slice;
 
 pub type MessageType = u8;
 pub type Parameter = u8;
@@ -18,6 +17,11 @@ pub mod message_type {
     pub const SET_PROTOCOL: MessageType = 0x7;
 
     pub const DATA: MessageType = 0xA;
+
+    #[deprecated(note = "GET_IDLE was removed from the protocol in HIDP 1.1")]
+    pub const GET_IDLE: MessageType = 0xC;
+    #[deprecated(note = "SET_IDLE was removed from the protocol in HIDP 1.1")]
+    pub const SET_IDLE: MessageType = 0xD;
 }
 
 pub mod handshake {
@@ -39,6 +43,200 @@ pub mod protocol {
     pub const REPORT: MessageType = 0x1;
 }
 
+pub mod hid_control {
+    use super::Parameter;
+
+    pub const NOP: Parameter = 0x0;
+    pub const HARD_RESET: Parameter = 0x1;
+    pub const SOFT_RESET: Parameter = 0x2;
+    pub const SUSPEND: Parameter = 0x3;
+    pub const EXIT_SUSPEND: Parameter = 0x4;
+    pub const VIRTUAL_CABLE_UNPLUG: Parameter = 0x5;
+}
+
+/// The category of report a GET_REPORT, SET_REPORT, or DATA message refers to, encoded in the
+/// low two bits of the message parameter.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ReportType {
+    Other = 0,
+    Input = 1,
+    Output = 2,
+    Feature = 3,
+}
+
+impl ReportType {
+    /// Extract the report type from the low two bits of a message parameter.
+    pub const fn from_parameter(parameter: Parameter) -> Self {
+        match parameter & 0x3 {
+            0 => ReportType::Other,
+            1 => ReportType::Input,
+            2 => ReportType::Output,
+            _ => ReportType::Feature,
+        }
+    }
+
+    /// Encode this report type into the low two bits of a message parameter.
+    pub const fn to_parameter(self) -> Parameter {
+        self as Parameter
+    }
+}
+
+/// Whether a device is operating in Boot or Report protocol, as carried by GET_PROTOCOL and
+/// SET_PROTOCOL messages.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ProtocolMode {
+    Boot = 0,
+    Report = 1,
+}
+
+impl ProtocolMode {
+    /// Decode a protocol mode from a message parameter, if it is one of the two legal values.
+    pub const fn from_parameter(parameter: Parameter) -> Option<Self> {
+        match parameter {
+            protocol::BOOT => Some(ProtocolMode::Boot),
+            protocol::REPORT => Some(ProtocolMode::Report),
+            _ => None,
+        }
+    }
+
+    /// Encode this protocol mode into a message parameter.
+    pub const fn to_parameter(self) -> Parameter {
+        self as Parameter
+    }
+}
+
+/// The header field an offending value was read from.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Field {
+    MessageType,
+    HandshakeParameter,
+    HidControlParameter,
+    ReportType,
+    ProtocolMode,
+}
+
+impl fmt::Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Field::MessageType => "message type",
+            Field::HandshakeParameter => "handshake parameter",
+            Field::HidControlParameter => "HID_CONTROL parameter",
+            Field::ReportType => "report type",
+            Field::ProtocolMode => "protocol mode",
+        })
+    }
+}
+
+/// Why a header field was rejected against the HIDP v1.1 tables.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ParseErrorKind {
+    /// The value is reserved for future use by the specification.
+    Reserved,
+    /// The value is defined by the specification but has been deprecated.
+    Deprecated,
+    /// The value is not a legal encoding for this field.
+    Invalid,
+}
+
+/// An error encountered while decoding a [`Message`] from its wire format.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ParseError {
+    kind: ParseErrorKind,
+    field: Field,
+    value: u8,
+}
+
+impl ParseError {
+    const fn new(kind: ParseErrorKind, field: Field, value: u8) -> Self {
+        ParseError { kind, field, value }
+    }
+
+    /// Returns why the value was rejected.
+    pub const fn kind(&self) -> ParseErrorKind {
+        self.kind
+    }
+
+    /// Returns which header field the offending value came from.
+    pub const fn field(&self) -> Field {
+        self.field
+    }
+
+    /// Returns the raw value that failed validation.
+    pub const fn value(&self) -> u8 {
+        self.value
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reason = match self.kind {
+            ParseErrorKind::Reserved => "reserved",
+            ParseErrorKind::Deprecated => "deprecated",
+            ParseErrorKind::Invalid => "invalid",
+        };
+        write!(f, "{} {:#x} is {}", self.field, self.value, reason)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+fn validate_handshake_parameter(parameter: Parameter) -> Result<(), ParseError> {
+    match parameter {
+        0x5..=0xD => Err(ParseError::new(ParseErrorKind::Reserved, Field::HandshakeParameter, parameter)),
+        _ => Ok(()),
+    }
+}
+
+fn validate_hid_control_parameter(parameter: Parameter) -> Result<(), ParseError> {
+    match parameter {
+        0x6..=0xF => Err(ParseError::new(ParseErrorKind::Reserved, Field::HidControlParameter, parameter)),
+        _ => Ok(()),
+    }
+}
+
+fn validate_protocol_mode(parameter: Parameter) -> Result<(), ParseError> {
+    match ProtocolMode::from_parameter(parameter) {
+        Some(_) => Ok(()),
+        None => Err(ParseError::new(ParseErrorKind::Invalid, Field::ProtocolMode, parameter)),
+    }
+}
+
+fn validate_report_parameter(parameter: Parameter) -> Result<(), ParseError> {
+    if parameter & 0x4 != 0 {
+        Err(ParseError::new(ParseErrorKind::Reserved, Field::ReportType, parameter))
+    } else {
+        Ok(())
+    }
+}
+
+/// Split a SET_REPORT/DATA/unsized-GET_REPORT payload into its leading Report ID, if any, and
+/// the report contents that follow it. The wire format alone can't say whether a device uses
+/// numbered reports, so the caller must say: when `numbered_reports` is set, a leading byte is
+/// read as a Report ID when present; otherwise the whole payload is treated as report contents.
+fn parse_unsized_report_payload(body: &[u8], numbered_reports: bool) -> ReportPayload {
+    if !numbered_reports {
+        return ReportPayload::with_body(None, Box::from(body));
+    }
+    match body {
+        [] => ReportPayload::with_body(None, Box::from([])),
+        [report_id, rest @ ..] => ReportPayload::with_body(Some(*report_id), Box::from(rest)),
+    }
+}
+
+/// Parse a GET_REPORT payload. When the size bit (0x08) is set, the payload is a 1-byte Report
+/// ID followed by a little-endian 2-byte buffer size; otherwise it is at most a 1-byte Report ID,
+/// per `numbered_reports` (see [`parse_unsized_report_payload`]).
+fn parse_get_report_payload(parameter: Parameter, body: &[u8], numbered_reports: bool) -> Result<ReportPayload, ParseError> {
+    if parameter & 0x8 != 0 {
+        match body {
+            [report_id, size_lo, size_hi] => Ok(ReportPayload::with_size(*report_id, u16::from_le_bytes([*size_lo, *size_hi]))),
+            _ => Err(ParseError::new(ParseErrorKind::Invalid, Field::ReportType, parameter)),
+        }
+    } else {
+        Ok(parse_unsized_report_payload(body, numbered_reports))
+    }
+}
+
 pub struct Header(MessageType, Parameter);
 
 impl Header {
@@ -72,36 +270,92 @@ impl From<Header> for u8 {
     }
 }
 
+/// The payload of a GET_REPORT, SET_REPORT, or DATA message. HIDP optionally prefixes a report
+/// body with a Report ID, for devices whose report descriptor defines numbered reports, and a
+/// size-querying GET_REPORT carries a requested buffer size instead of report contents.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ReportPayload {
+    report_id: Option<u8>,
+    size: Option<u16>,
+    body: Box<[u8]>,
+}
+
+impl ReportPayload {
+    /// Construct a payload carrying report contents, optionally prefixed with a Report ID.
+    pub fn with_body(report_id: Option<u8>, body: Box<[u8]>) -> Self {
+        ReportPayload { report_id, size: None, body }
+    }
+
+    /// Construct a GET_REPORT payload asking the device to report back at most `size` bytes of
+    /// the report identified by `report_id`.
+    pub fn with_size(report_id: u8, size: u16) -> Self {
+        ReportPayload { report_id: Some(report_id), size: Some(size), body: Box::from([]) }
+    }
+
+    /// Returns the Report ID prefix, if the device uses numbered reports.
+    pub fn report_id(&self) -> Option<u8> {
+        self.report_id
+    }
+
+    /// Returns the requested buffer size, if this is a size-querying GET_REPORT.
+    pub fn size(&self) -> Option<u16> {
+        self.size
+    }
+
+    /// Returns the report contents, excluding any Report ID or buffer size.
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+}
+
 /// HID protocol messages. Deprecated messages are unsupported.
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum Message {
     Handshake(Parameter),
     HidControl(Parameter),
-    GetReport(Parameter, Box<[u8]>),
-    SetReport(Parameter, Box<[u8]>),
+    GetReport(Parameter, ReportPayload),
+    SetReport(Parameter, ReportPayload),
     GetProtocol(Parameter),
     SetProtocol(Parameter),
-    Data(Parameter, Box<[u8]>),
+    Data(Parameter, ReportPayload),
 }
 
 impl Message {
     /// Construct a new Message for a report other than an input, output, or feature report.
     pub fn new_data_other(data: Box<[u8]>) -> Self{
-        Self::Data(0, data)
+        Self::Data(ReportType::Other.to_parameter(), ReportPayload::with_body(None, data))
     }
     /// Construct a new Message for an input report.
     pub fn new_data_input(data: Box<[u8]>) -> Self{
-        Self::Data(1, data)
+        Self::Data(ReportType::Input.to_parameter(), ReportPayload::with_body(None, data))
     }
     /// Construct a new Message for an output report.
     pub fn new_data_output(data: Box<[u8]>) -> Self{
-        Self::Data(2, data)
+        Self::Data(ReportType::Output.to_parameter(), ReportPayload::with_body(None, data))
     }
     /// Construct a new Message for a feature report.
     pub fn new_data_feature(data: Box<[u8]>) -> Self{
-        Self::Data(3, data)
+        Self::Data(ReportType::Feature.to_parameter(), ReportPayload::with_body(None, data))
+    }
+
+    /// Construct a GET_REPORT requesting the device's current value for `report_type`,
+    /// optionally addressed by Report ID.
+    pub fn new_get_report(report_type: ReportType, report_id: Option<u8>) -> Self {
+        Self::GetReport(report_type.to_parameter(), ReportPayload::with_body(report_id, Box::from([])))
+    }
+
+    /// Construct a GET_REPORT asking the device for its `report_type` report for `report_id`,
+    /// limited to at most `size` bytes. This is the form hidapi's feature-report fetch uses,
+    /// where the caller supplies a buffer prefixed with a Report ID.
+    pub fn new_get_report_sized(report_type: ReportType, report_id: u8, size: u16) -> Self {
+        Self::GetReport(report_type.to_parameter() | 0x8, ReportPayload::with_size(report_id, size))
     }
 
+    /// Construct a SET_REPORT delivering `body` as the device's `report_type` report,
+    /// optionally prefixed with a Report ID.
+    pub fn new_set_report(report_type: ReportType, report_id: Option<u8>, body: Box<[u8]>) -> Self {
+        Self::SetReport(report_type.to_parameter(), ReportPayload::with_body(report_id, body))
+    }
 
     pub fn parameter(&self) -> Parameter {
         match self {
@@ -128,40 +382,836 @@ impl Message {
         Header::new(self.message_type(), self.parameter())
     }
 
-    /// Return this message's data, if it exists.
-    pub fn data<'a>(&'a self) -> Option<&'a [u8]> {
+    /// Return this message's report contents, if it carries any, excluding any Report ID or
+    /// buffer size.
+    pub fn data(&self) -> Option<&[u8]> {
         match self {
-            Self::GetReport(_, data) | Self::SetReport(_, data) | Self::Data(_, data) =>
-                Some(data),
+            Self::GetReport(_, payload) | Self::SetReport(_, payload) | Self::Data(_, payload) =>
+                Some(payload.body()),
             _ => None,
         }
     }
 
-    pub fn read_from(mut data: &[u8]) -> io::Result<Self> {
-        let mut header_byte = 0u8;
-        data.read_exact(slice::from_mut(&mut header_byte))?;
+    /// Return this message's full report payload, if it carries one.
+    pub fn report_payload(&self) -> Option<&ReportPayload> {
+        match self {
+            Self::GetReport(_, payload) | Self::SetReport(_, payload) | Self::Data(_, payload) =>
+                Some(payload),
+            _ => None,
+        }
+    }
+
+    /// Parse a Message out of its wire representation, validating the message type and the
+    /// HANDSHAKE, HID_CONTROL, report, and SET_PROTOCOL parameters against the HIDP v1.1 tables.
+    /// Reserved, deprecated, and out-of-range values in those fields are reported as a
+    /// [`ParseError`] rather than silently accepted; the GET_PROTOCOL parameter is not checked.
+    ///
+    /// `numbered_reports` must reflect whether the device's report descriptor defines numbered
+    /// reports: the wire format gives no way to tell a leading Report ID byte apart from the
+    /// first byte of report contents, so GET_REPORT, SET_REPORT, and DATA payloads are only
+    /// split on a leading Report ID when the caller says the device uses one.
+    pub fn read_from(data: &[u8], numbered_reports: bool) -> Result<Self, ParseError> {
+        let header_byte = *data.first()
+            .ok_or_else(|| ParseError::new(ParseErrorKind::Invalid, Field::MessageType, 0))?;
         let header = Header::from(header_byte);
+        let body = &data[1..];
+        let message_type = header.message_type();
+        let parameter = header.parameter();
+
+        #[allow(deprecated)]
+        match message_type {
+            message_type::GET_IDLE | message_type::SET_IDLE =>
+                return Err(ParseError::new(ParseErrorKind::Deprecated, Field::MessageType, message_type)),
+            0x2 | 0x3 | 0x8 | 0x9 | 0xB | 0xE | 0xF =>
+                return Err(ParseError::new(ParseErrorKind::Reserved, Field::MessageType, message_type)),
+            _ => {},
+        }
 
-        Ok(match header.message_type() {
-            message_type::HANDSHAKE => Message::Handshake(header.parameter()),
-            message_type::HID_CONTROL => Message::HidControl(header.parameter()),
-            message_type::GET_REPORT => Message::GetReport(header.parameter(), Box::from(data)),
-            message_type::SET_REPORT => Message::SetReport(header.parameter(), Box::from(data)),
-            message_type::GET_PROTOCOL => Message::GetProtocol(header.parameter()),
-            message_type::SET_PROTOCOL => Message::SetProtocol(header.parameter()),
-            message_type::DATA => Message::Data(header.parameter(), Box::from(data)),
-            _ => {
-                return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid message type encountered"));
+        Ok(match message_type {
+            message_type::HANDSHAKE => {
+                validate_handshake_parameter(parameter)?;
+                Message::Handshake(parameter)
             },
+            message_type::HID_CONTROL => {
+                validate_hid_control_parameter(parameter)?;
+                Message::HidControl(parameter)
+            },
+            message_type::GET_REPORT => {
+                validate_report_parameter(parameter)?;
+                Message::GetReport(parameter, parse_get_report_payload(parameter, body, numbered_reports)?)
+            },
+            message_type::SET_REPORT => {
+                validate_report_parameter(parameter)?;
+                Message::SetReport(parameter, parse_unsized_report_payload(body, numbered_reports))
+            },
+            message_type::GET_PROTOCOL => Message::GetProtocol(parameter),
+            message_type::SET_PROTOCOL => {
+                validate_protocol_mode(parameter)?;
+                Message::SetProtocol(parameter)
+            },
+            message_type::DATA => {
+                validate_report_parameter(parameter)?;
+                Message::Data(parameter, parse_unsized_report_payload(body, numbered_reports))
+            },
+            _ => unreachable!("message type already validated above"),
         })
     }
 
     pub fn as_bytes(&self) -> Box<[u8]> {
-        let parameter_iter = iter::once(self.header().into());
-        if let Some(data) = self.data() {
-            parameter_iter.chain(data.into_iter().copied()).collect()
+        let header_byte: u8 = self.header().into();
+        match self.report_payload() {
+            Some(payload) => iter::once(header_byte)
+                .chain(payload.report_id())
+                .chain(payload.size().into_iter().flat_map(u16::to_le_bytes))
+                .chain(payload.body().iter().copied())
+                .collect(),
+            None => iter::once(header_byte).collect(),
+        }
+    }
+}
+
+/// A HANDSHAKE response indicating a request could not be completed, returned by a
+/// [`RequestHandler`] so a [`Session`] can translate it into the matching reply message.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum HandshakeError {
+    NotReady,
+    InvalidReportId,
+    UnsupportedRequest,
+    InvalidParameter,
+    Unknown,
+    Fatal,
+}
+
+impl HandshakeError {
+    /// The HANDSHAKE parameter this error is reported as.
+    pub const fn parameter(self) -> Parameter {
+        match self {
+            HandshakeError::NotReady => handshake::NOT_READY,
+            HandshakeError::InvalidReportId => handshake::ERR_INVAILD_REPORT_ID,
+            HandshakeError::UnsupportedRequest => handshake::ERR_UNSUPPORTED_REQUEST,
+            HandshakeError::InvalidParameter => handshake::ERR_INVALID_PARAMETER,
+            HandshakeError::Unknown => handshake::ERR_UNKNOWN,
+            HandshakeError::Fatal => handshake::ERR_FATAL,
+        }
+    }
+}
+
+impl fmt::Display for HandshakeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            HandshakeError::NotReady => "device not ready",
+            HandshakeError::InvalidReportId => "invalid report ID",
+            HandshakeError::UnsupportedRequest => "unsupported request",
+            HandshakeError::InvalidParameter => "invalid parameter",
+            HandshakeError::Unknown => "unknown error",
+            HandshakeError::Fatal => "fatal error",
+        })
+    }
+}
+
+impl std::error::Error for HandshakeError {}
+
+/// Implemented by a HID device to answer incoming protocol requests. A [`Session`] dispatches
+/// parsed [`Message`]s to these methods and turns the result into the matching reply message.
+pub trait RequestHandler {
+    /// Return the device's current value for the given report, optionally truncated to
+    /// `buf_size` bytes.
+    fn get_report(&self, report_type: ReportType, report_id: Option<u8>, buf_size: Option<u16>) -> Result<Box<[u8]>, HandshakeError>;
+
+    /// Store `body` as the device's report of the given type.
+    fn set_report(&self, report_type: ReportType, report_id: Option<u8>, body: &[u8]) -> Result<(), HandshakeError>;
+
+    /// Return the device's current protocol mode.
+    fn get_protocol(&self) -> Result<ProtocolMode, HandshakeError>;
+
+    /// Switch the device into the given protocol mode.
+    fn set_protocol(&self, protocol_mode: ProtocolMode) -> Result<(), HandshakeError>;
+}
+
+/// Connection state for a HID transport, mirroring the Android `BthhConnectionState` states.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ConnectionState {
+    Connecting,
+    Connected,
+    Disconnecting,
+    Disconnected,
+}
+
+/// Tracks protocol mode and connection state for one HID transport connection across messages.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Connection {
+    state: ConnectionState,
+    protocol_mode: ProtocolMode,
+}
+
+impl Connection {
+    /// Construct a Connection for a transport that has just started connecting, defaulting to
+    /// Report protocol as required by the HIDP specification.
+    pub const fn new() -> Self {
+        Connection { state: ConnectionState::Connecting, protocol_mode: ProtocolMode::Report }
+    }
+
+    /// Construct a Connection for a transport that has just started connecting, seeded with a
+    /// known current protocol mode rather than assuming the Report protocol default.
+    pub const fn with_protocol_mode(protocol_mode: ProtocolMode) -> Self {
+        Connection { state: ConnectionState::Connecting, protocol_mode }
+    }
+
+    /// Returns the current connection state.
+    pub const fn state(&self) -> ConnectionState {
+        self.state
+    }
+
+    /// Returns the current protocol mode: the one this Connection was constructed with, or the
+    /// last one set via SET_PROTOCOL or restored to Report by a HARD_RESET or SOFT_RESET.
+    pub const fn protocol_mode(&self) -> ProtocolMode {
+        self.protocol_mode
+    }
+
+    /// Mark the underlying transport as fully connected.
+    pub fn connect(&mut self) {
+        self.state = ConnectionState::Connected;
+    }
+
+    /// Mark the underlying transport as having finished disconnecting.
+    pub fn disconnected(&mut self) {
+        self.state = ConnectionState::Disconnected;
+    }
+
+    /// Interpret a HID_CONTROL parameter, transitioning state as the HIDP specification requires.
+    fn handle_hid_control(&mut self, parameter: Parameter) {
+        match parameter {
+            hid_control::VIRTUAL_CABLE_UNPLUG => self.state = ConnectionState::Disconnecting,
+            hid_control::HARD_RESET | hid_control::SOFT_RESET => self.protocol_mode = ProtocolMode::Report,
+            _ => {},
+        }
+    }
+
+    /// Returns an error if `report_type` may not be requested in the current connection/protocol
+    /// state, or `Ok(())` if the request may proceed.
+    fn validate_report_request(&self, report_type: ReportType) -> Result<(), HandshakeError> {
+        if self.state != ConnectionState::Connected {
+            Err(HandshakeError::NotReady)
+        } else if self.protocol_mode == ProtocolMode::Boot && report_type == ReportType::Feature {
+            Err(HandshakeError::UnsupportedRequest)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl Default for Connection {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Dispatches parsed [`Message`]s to a [`RequestHandler`], tracking [`Connection`] state across
+/// calls, and produces the reply to send back.
+pub struct Session<H> {
+    handler: H,
+    connection: Connection,
+}
+
+impl<H: RequestHandler> Session<H> {
+    /// Construct a Session that answers requests with `handler`, resyncing the tracked
+    /// [`Connection`]'s protocol mode from `handler.get_protocol()` rather than assuming the
+    /// Report protocol default.
+    pub fn new(handler: H) -> Self {
+        let protocol_mode = handler.get_protocol().unwrap_or(ProtocolMode::Report);
+        Session { handler, connection: Connection::with_protocol_mode(protocol_mode) }
+    }
+
+    /// Returns the handler this session dispatches requests to.
+    pub const fn handler(&self) -> &H {
+        &self.handler
+    }
+
+    /// Returns the connection state this session is tracking.
+    pub const fn connection(&self) -> &Connection {
+        &self.connection
+    }
+
+    /// Returns the connection state this session is tracking, for transport-driven transitions
+    /// like [`Connection::connect`].
+    pub fn connection_mut(&mut self) -> &mut Connection {
+        &mut self.connection
+    }
+
+    /// Handle an incoming Message, returning the reply Message to send back, if any. Messages
+    /// that expect no reply (HANDSHAKE, HID_CONTROL, DATA) produce `None`.
+    pub fn handle(&mut self, message: &Message) -> Option<Message> {
+        match message {
+            Message::HidControl(parameter) => {
+                self.connection.handle_hid_control(*parameter);
+                None
+            },
+            Message::GetReport(parameter, payload) => {
+                let report_type = ReportType::from_parameter(*parameter);
+                Some(match self.connection.validate_report_request(report_type)
+                    .and_then(|()| self.handler.get_report(report_type, payload.report_id(), payload.size()))
+                {
+                    Ok(body) => Message::Data(report_type.to_parameter(), ReportPayload::with_body(payload.report_id(), body)),
+                    Err(error) => Message::Handshake(error.parameter()),
+                })
+            },
+            Message::SetReport(parameter, payload) => {
+                let report_type = ReportType::from_parameter(*parameter);
+                Some(match self.connection.validate_report_request(report_type)
+                    .and_then(|()| self.handler.set_report(report_type, payload.report_id(), payload.body()))
+                {
+                    Ok(()) => Message::Handshake(handshake::SUCCESSFUL),
+                    Err(error) => Message::Handshake(error.parameter()),
+                })
+            },
+            Message::GetProtocol(_) => {
+                Some(Message::Data(ReportType::Other.to_parameter(),
+                    ReportPayload::with_body(None, Box::from([self.connection.protocol_mode().to_parameter()]))))
+            },
+            Message::SetProtocol(parameter) => {
+                Some(match ProtocolMode::from_parameter(*parameter) {
+                    Some(mode) => match self.handler.set_protocol(mode) {
+                        Ok(()) => {
+                            self.connection.protocol_mode = mode;
+                            Message::Handshake(handshake::SUCCESSFUL)
+                        },
+                        Err(error) => Message::Handshake(error.parameter()),
+                    },
+                    None => Message::Handshake(handshake::ERR_INVALID_PARAMETER),
+                })
+            },
+            _ => None,
+        }
+    }
+}
+
+/// Builds the SDP service record a HID peripheral must advertise to be discovered, per the
+/// Bluetooth HID Profile specification's service discovery section.
+pub mod sdp {
+    /// Protocol and service-class UUIDs referenced by a HID service record.
+    pub mod uuid {
+        pub const L2CAP: u16 = 0x0100;
+        pub const HIDP: u16 = 0x0011;
+        pub const HUMAN_INTERFACE_DEVICE: u16 = 0x1124;
+    }
+
+    /// SDP attribute IDs used in a HID service record.
+    pub mod attribute {
+        pub const SERVICE_CLASS_ID_LIST: u16 = 0x0001;
+        pub const PROTOCOL_DESCRIPTOR_LIST: u16 = 0x0004;
+        pub const LANGUAGE_BASE_ATTRIBUTE_ID_LIST: u16 = 0x0006;
+        pub const ADDITIONAL_PROTOCOL_DESCRIPTOR_LISTS: u16 = 0x000D;
+        pub const HID_PARSER_VERSION: u16 = 0x0201;
+        pub const HID_DESCRIPTOR_LIST: u16 = 0x0206;
+        pub const HID_LANG_ID_BASE_LIST: u16 = 0x0207;
+        pub const HID_BOOT_DEVICE: u16 = 0x020E;
+    }
+
+    /// The L2CAP PSM used for the HID control channel.
+    pub const CONTROL_PSM: u16 = 0x11;
+    /// The L2CAP PSM used for the HID interrupt channel.
+    pub const INTERRUPT_PSM: u16 = 0x13;
+
+    /// An SDP data element, serializing to the data-element byte format defined by the
+    /// Bluetooth SDP specification (Core spec, Part E, section 3.2).
+    #[derive(Clone, Debug, Eq, PartialEq)]
+    pub enum DataElement {
+        UnsignedInt8(u8),
+        UnsignedInt16(u16),
+        UnsignedInt32(u32),
+        Uuid16(u16),
+        Boolean(bool),
+        Text(Box<[u8]>),
+        Sequence(Vec<DataElement>),
+    }
+
+    impl DataElement {
+        /// Append this data element's SDP wire representation to `out`.
+        pub fn write_to(&self, out: &mut Vec<u8>) {
+            match self {
+                DataElement::UnsignedInt8(v) => {
+                    out.push(0x08);
+                    out.push(*v);
+                },
+                DataElement::UnsignedInt16(v) => {
+                    out.push(0x09);
+                    out.extend_from_slice(&v.to_be_bytes());
+                },
+                DataElement::UnsignedInt32(v) => {
+                    out.push(0x0A);
+                    out.extend_from_slice(&v.to_be_bytes());
+                },
+                DataElement::Uuid16(v) => {
+                    out.push(0x19);
+                    out.extend_from_slice(&v.to_be_bytes());
+                },
+                DataElement::Boolean(v) => {
+                    out.push(0x28);
+                    out.push(*v as u8);
+                },
+                DataElement::Text(bytes) => {
+                    write_header(out, 0x4, bytes.len());
+                    out.extend_from_slice(bytes);
+                },
+                DataElement::Sequence(elements) => {
+                    let mut body = Vec::new();
+                    for element in elements {
+                        element.write_to(&mut body);
+                    }
+                    write_header(out, 0x6, body.len());
+                    out.extend_from_slice(&body);
+                },
+            }
+        }
+
+        /// Serialize this data element to its SDP wire representation.
+        pub fn to_bytes(&self) -> Box<[u8]> {
+            let mut out = Vec::new();
+            self.write_to(&mut out);
+            out.into_boxed_slice()
+        }
+    }
+
+    /// Write an SDP data element header for `type_descriptor`, sized to hold `len` bytes.
+    fn write_header(out: &mut Vec<u8>, type_descriptor: u8, len: usize) {
+        if let Ok(len) = u8::try_from(len) {
+            out.push(type_descriptor << 3 | 0x5);
+            out.push(len);
+        } else if let Ok(len) = u16::try_from(len) {
+            out.push(type_descriptor << 3 | 0x6);
+            out.extend_from_slice(&len.to_be_bytes());
         } else {
-            parameter_iter.collect()
+            out.push(type_descriptor << 3 | 0x7);
+            out.extend_from_slice(&(len as u32).to_be_bytes());
+        }
+    }
+
+    /// A Bluetooth HID service record, describing a HID peripheral for SDP advertisement.
+    #[derive(Clone, Debug, Eq, PartialEq)]
+    pub struct ServiceRecord {
+        report_descriptor: Box<[u8]>,
+        parser_version: u16,
+        boot_device: bool,
+        control_psm: u16,
+        interrupt_psm: u16,
+    }
+
+    impl ServiceRecord {
+        /// Construct a service record for a device whose report descriptor is
+        /// `report_descriptor`, using the default control/interrupt PSMs and HIDP parser
+        /// version 1.1.1.
+        pub fn new(report_descriptor: Box<[u8]>) -> Self {
+            ServiceRecord {
+                report_descriptor,
+                parser_version: 0x0111,
+                boot_device: false,
+                control_psm: CONTROL_PSM,
+                interrupt_psm: INTERRUPT_PSM,
+            }
+        }
+
+        /// Set the HIDParserVersion attribute, encoded as major.minor.subminor BCD.
+        pub fn set_parser_version(&mut self, parser_version: u16) {
+            self.parser_version = parser_version;
+        }
+
+        /// Set whether this device supports the HID Boot Protocol.
+        pub fn set_boot_device(&mut self, boot_device: bool) {
+            self.boot_device = boot_device;
+        }
+
+        fn protocol_descriptor_list(psm: u16) -> DataElement {
+            DataElement::Sequence(vec![
+                DataElement::Sequence(vec![DataElement::Uuid16(uuid::L2CAP), DataElement::UnsignedInt16(psm)]),
+                DataElement::Sequence(vec![DataElement::Uuid16(uuid::HIDP)]),
+            ])
+        }
+
+        /// Build the attribute ID/value pairs that make up this service record.
+        pub fn attributes(&self) -> Vec<(u16, DataElement)> {
+            vec![
+                (attribute::SERVICE_CLASS_ID_LIST,
+                    DataElement::Sequence(vec![DataElement::Uuid16(uuid::HUMAN_INTERFACE_DEVICE)])),
+                (attribute::PROTOCOL_DESCRIPTOR_LIST, Self::protocol_descriptor_list(self.control_psm)),
+                (attribute::ADDITIONAL_PROTOCOL_DESCRIPTOR_LISTS,
+                    DataElement::Sequence(vec![Self::protocol_descriptor_list(self.interrupt_psm)])),
+                (attribute::LANGUAGE_BASE_ATTRIBUTE_ID_LIST,
+                    DataElement::Sequence(vec![
+                        DataElement::UnsignedInt16(0x0409), // English (United States)
+                        DataElement::UnsignedInt16(0x006A), // UTF-8
+                        DataElement::UnsignedInt16(0x0100), // base attribute ID
+                    ])),
+                (attribute::HID_DESCRIPTOR_LIST,
+                    DataElement::Sequence(vec![DataElement::Sequence(vec![
+                        DataElement::UnsignedInt8(0x22), // report descriptor type
+                        DataElement::Text(self.report_descriptor.clone()),
+                    ])])),
+                (attribute::HID_LANG_ID_BASE_LIST,
+                    DataElement::Sequence(vec![DataElement::Sequence(vec![
+                        DataElement::UnsignedInt16(0x0409), // English (United States)
+                        DataElement::UnsignedInt16(0x0100), // language base
+                    ])])),
+                (attribute::HID_PARSER_VERSION, DataElement::UnsignedInt16(self.parser_version)),
+                (attribute::HID_BOOT_DEVICE, DataElement::Boolean(self.boot_device)),
+            ]
+        }
+
+        /// Serialize this service record as a single SDP data-element sequence of attribute
+        /// ID/value pairs, ready to hand to an SDP server.
+        pub fn to_bytes(&self) -> Box<[u8]> {
+            let elements = self.attributes().into_iter()
+                .flat_map(|(id, value)| [DataElement::UnsignedInt16(id), value])
+                .collect();
+            DataElement::Sequence(elements).to_bytes()
+        }
+    }
+}
+
+#[cfg(test)]
+mod sdp_tests {
+    use super::sdp::*;
+
+    #[test]
+    fn unsigned_int_elements_encode_big_endian() {
+        assert_eq!(&*DataElement::UnsignedInt8(0x22).to_bytes(), &[0x08, 0x22]);
+        assert_eq!(&*DataElement::UnsignedInt16(0x0409).to_bytes(), &[0x09, 0x04, 0x09]);
+        assert_eq!(&*DataElement::UnsignedInt32(0x01020304).to_bytes(), &[0x0A, 0x01, 0x02, 0x03, 0x04]);
+    }
+
+    #[test]
+    fn uuid_and_boolean_elements_encode() {
+        assert_eq!(&*DataElement::Uuid16(0x1124).to_bytes(), &[0x19, 0x11, 0x24]);
+        assert_eq!(&*DataElement::Boolean(true).to_bytes(), &[0x28, 0x01]);
+        assert_eq!(&*DataElement::Boolean(false).to_bytes(), &[0x28, 0x00]);
+    }
+
+    #[test]
+    fn sequence_and_text_elements_carry_a_length_prefixed_header() {
+        assert_eq!(&*DataElement::Text(Box::from([0x05, 0x01])).to_bytes(), &[0x25, 0x02, 0x05, 0x01]);
+        assert_eq!(
+            &*DataElement::Sequence(vec![DataElement::Uuid16(0x0011)]).to_bytes(),
+            &[0x35, 0x03, 0x19, 0x00, 0x11],
+        );
+    }
+
+    #[test]
+    fn service_record_serializes_to_the_expected_sdp_bytes() {
+        let record = ServiceRecord::new(Box::from([0x05, 0x01]));
+        let bytes = record.to_bytes();
+        assert_eq!(&*bytes, &[
+            0x35, 0x61,
+            0x09, 0x00, 0x01, 0x35, 0x03, 0x19, 0x11, 0x24,
+            0x09, 0x00, 0x04, 0x35, 0x0D, 0x35, 0x06, 0x19, 0x01, 0x00, 0x09, 0x00, 0x11, 0x35, 0x03, 0x19, 0x00, 0x11,
+            0x09, 0x00, 0x0D, 0x35, 0x0F, 0x35, 0x0D, 0x35, 0x06, 0x19, 0x01, 0x00, 0x09, 0x00, 0x13, 0x35, 0x03, 0x19, 0x00, 0x11,
+            0x09, 0x00, 0x06, 0x35, 0x09, 0x09, 0x04, 0x09, 0x09, 0x00, 0x6A, 0x09, 0x01, 0x00,
+            0x09, 0x02, 0x06, 0x35, 0x08, 0x35, 0x06, 0x08, 0x22, 0x25, 0x02, 0x05, 0x01,
+            0x09, 0x02, 0x07, 0x35, 0x08, 0x35, 0x06, 0x09, 0x04, 0x09, 0x09, 0x01, 0x00,
+            0x09, 0x02, 0x01, 0x09, 0x01, 0x11,
+            0x09, 0x02, 0x0E, 0x28, 0x00,
+        ]);
+    }
+
+    #[test]
+    fn service_record_reflects_boot_device_and_parser_version_overrides() {
+        let mut record = ServiceRecord::new(Box::from([]));
+        record.set_boot_device(true);
+        record.set_parser_version(0x0105);
+        let bytes = record.to_bytes();
+
+        let version_pos = bytes.windows(3).position(|window| window == [0x09, 0x02, 0x01]).unwrap();
+        assert_eq!(&bytes[version_pos + 3..version_pos + 6], &[0x09, 0x01, 0x05]);
+
+        let boot_device_pos = bytes.windows(3).position(|window| window == [0x09, 0x02, 0x0E]).unwrap();
+        assert_eq!(&bytes[boot_device_pos + 3..boot_device_pos + 5], &[0x28, 0x01]);
+    }
+}
+
+#[cfg(test)]
+mod connection_tests {
+    use super::*;
+
+    #[test]
+    fn new_connection_starts_connecting_in_report_mode() {
+        let connection = Connection::new();
+        assert_eq!(connection.state(), ConnectionState::Connecting);
+        assert_eq!(connection.protocol_mode(), ProtocolMode::Report);
+    }
+
+    #[test]
+    fn virtual_cable_unplug_moves_to_disconnecting() {
+        let mut connection = Connection::new();
+        connection.connect();
+        connection.handle_hid_control(hid_control::VIRTUAL_CABLE_UNPLUG);
+        assert_eq!(connection.state(), ConnectionState::Disconnecting);
+    }
+
+    #[test]
+    fn disconnected_follows_disconnecting() {
+        let mut connection = Connection::new();
+        connection.connect();
+        connection.handle_hid_control(hid_control::VIRTUAL_CABLE_UNPLUG);
+        connection.disconnected();
+        assert_eq!(connection.state(), ConnectionState::Disconnected);
+    }
+
+    #[test]
+    fn hard_reset_restores_report_protocol() {
+        let mut connection = Connection::with_protocol_mode(ProtocolMode::Boot);
+        connection.handle_hid_control(hid_control::HARD_RESET);
+        assert_eq!(connection.protocol_mode(), ProtocolMode::Report);
+    }
+
+    #[test]
+    fn soft_reset_restores_report_protocol() {
+        let mut connection = Connection::with_protocol_mode(ProtocolMode::Boot);
+        connection.handle_hid_control(hid_control::SOFT_RESET);
+        assert_eq!(connection.protocol_mode(), ProtocolMode::Report);
+    }
+
+    #[test]
+    fn unconnected_report_requests_are_not_ready() {
+        let connection = Connection::new();
+        assert_eq!(connection.validate_report_request(ReportType::Input), Err(HandshakeError::NotReady));
+    }
+
+    #[test]
+    fn boot_mode_rejects_feature_reports() {
+        let mut connection = Connection::with_protocol_mode(ProtocolMode::Boot);
+        connection.connect();
+        assert_eq!(connection.validate_report_request(ReportType::Feature), Err(HandshakeError::UnsupportedRequest));
+        assert_eq!(connection.validate_report_request(ReportType::Input), Ok(()));
+    }
+}
+
+#[cfg(test)]
+mod session_tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    type SetReportCall = (ReportType, Option<u8>, Vec<u8>);
+
+    struct MockHandler {
+        protocol_mode: ProtocolMode,
+        last_set_report: RefCell<Option<SetReportCall>>,
+    }
+
+    impl MockHandler {
+        fn new() -> Self {
+            MockHandler { protocol_mode: ProtocolMode::Report, last_set_report: RefCell::new(None) }
+        }
+    }
+
+    impl RequestHandler for MockHandler {
+        fn get_report(&self, report_type: ReportType, report_id: Option<u8>, _buf_size: Option<u16>) -> Result<Box<[u8]>, HandshakeError> {
+            match (report_type, report_id) {
+                (ReportType::Feature, Some(9)) => Ok(Box::from([0x42])),
+                _ => Err(HandshakeError::InvalidReportId),
+            }
+        }
+
+        fn set_report(&self, report_type: ReportType, report_id: Option<u8>, body: &[u8]) -> Result<(), HandshakeError> {
+            *self.last_set_report.borrow_mut() = Some((report_type, report_id, body.to_vec()));
+            Ok(())
+        }
+
+        fn get_protocol(&self) -> Result<ProtocolMode, HandshakeError> {
+            Ok(self.protocol_mode)
+        }
+
+        fn set_protocol(&self, _protocol_mode: ProtocolMode) -> Result<(), HandshakeError> {
+            Ok(())
+        }
+    }
+
+    fn connected_session() -> Session<MockHandler> {
+        let mut session = Session::new(MockHandler::new());
+        session.connection_mut().connect();
+        session
+    }
+
+    #[test]
+    fn new_session_seeds_protocol_mode_from_handler() {
+        let session = Session::new(MockHandler { protocol_mode: ProtocolMode::Boot, ..MockHandler::new() });
+        assert_eq!(session.connection().protocol_mode(), ProtocolMode::Boot);
+    }
+
+    #[test]
+    fn get_report_dispatches_to_handler_success() {
+        let mut session = connected_session();
+        let request = Message::new_get_report(ReportType::Feature, Some(9));
+        let reply = session.handle(&request).unwrap();
+        assert_eq!(reply, Message::Data(ReportType::Feature.to_parameter(),
+            ReportPayload::with_body(Some(9), Box::from([0x42]))));
+    }
+
+    #[test]
+    fn get_report_dispatches_to_handler_error() {
+        let mut session = connected_session();
+        let request = Message::new_get_report(ReportType::Feature, Some(1));
+        let reply = session.handle(&request).unwrap();
+        assert_eq!(reply, Message::Handshake(handshake::ERR_INVAILD_REPORT_ID));
+    }
+
+    #[test]
+    fn get_report_before_connected_is_not_ready() {
+        let mut session = Session::new(MockHandler::new());
+        let request = Message::new_get_report(ReportType::Feature, Some(9));
+        let reply = session.handle(&request).unwrap();
+        assert_eq!(reply, Message::Handshake(handshake::NOT_READY));
+    }
+
+    #[test]
+    fn set_report_passes_through_the_decoded_report_id() {
+        let mut session = connected_session();
+        let request = Message::new_set_report(ReportType::Output, Some(5), Box::from([1, 2, 3]));
+        let reply = session.handle(&request).unwrap();
+        assert_eq!(reply, Message::Handshake(handshake::SUCCESSFUL));
+        assert_eq!(
+            *session.handler().last_set_report.borrow(),
+            Some((ReportType::Output, Some(5), vec![1, 2, 3])),
+        );
+    }
+
+    #[test]
+    fn set_protocol_updates_tracked_connection_mode() {
+        let mut session = connected_session();
+        let reply = session.handle(&Message::SetProtocol(protocol::BOOT)).unwrap();
+        assert_eq!(reply, Message::Handshake(handshake::SUCCESSFUL));
+        assert_eq!(session.connection().protocol_mode(), ProtocolMode::Boot);
+    }
+
+    #[test]
+    fn get_protocol_is_answered_locally_without_calling_the_handler() {
+        let mut session = connected_session();
+        // The handler still reports Report protocol; only the tracked connection says Boot.
+        session.connection_mut().protocol_mode = ProtocolMode::Boot;
+        let reply = session.handle(&Message::GetProtocol(0)).unwrap();
+        assert_eq!(reply, Message::Data(ReportType::Other.to_parameter(),
+            ReportPayload::with_body(None, Box::from([ProtocolMode::Boot.to_parameter()]))));
+    }
+}
+
+#[cfg(test)]
+mod report_payload_tests {
+    use super::*;
+
+    #[test]
+    fn report_type_round_trips_through_parameter() {
+        for report_type in [ReportType::Other, ReportType::Input, ReportType::Output, ReportType::Feature] {
+            assert_eq!(ReportType::from_parameter(report_type.to_parameter()), report_type);
         }
     }
+
+    #[test]
+    fn set_report_with_report_id_round_trips() {
+        let message = Message::new_set_report(ReportType::Output, Some(5), Box::from([1, 2, 3]));
+        let bytes = message.as_bytes();
+        assert_eq!(&*bytes, &[0x52, 5, 1, 2, 3]);
+        assert_eq!(Message::read_from(&bytes, true).unwrap(), message);
+    }
+
+    #[test]
+    fn set_report_without_report_id_round_trips() {
+        let message = Message::new_set_report(ReportType::Output, None, Box::from([]));
+        let bytes = message.as_bytes();
+        assert_eq!(&*bytes, &[0x52]);
+        assert_eq!(Message::read_from(&bytes, false).unwrap(), message);
+    }
+
+    #[test]
+    fn set_report_with_non_empty_body_and_no_numbered_reports_round_trips() {
+        let message = Message::new_set_report(ReportType::Output, None, Box::from([1, 2, 3]));
+        let bytes = message.as_bytes();
+        assert_eq!(&*bytes, &[0x52, 1, 2, 3]);
+        assert_eq!(Message::read_from(&bytes, false).unwrap(), message);
+    }
+
+    #[test]
+    fn data_with_report_id_round_trips() {
+        let message = Message::Data(ReportType::Input.to_parameter(),
+            ReportPayload::with_body(Some(7), Box::from([9, 9])));
+        let bytes = message.as_bytes();
+        assert_eq!(&*bytes, &[0xA1, 7, 9, 9]);
+        assert_eq!(Message::read_from(&bytes, true).unwrap(), message);
+    }
+
+    #[test]
+    fn data_without_report_id_and_non_empty_body_round_trips() {
+        let message = Message::new_data_input(Box::from([1, 2, 3]));
+        let bytes = message.as_bytes();
+        assert_eq!(&*bytes, &[0xA1, 1, 2, 3]);
+        assert_eq!(Message::read_from(&bytes, false).unwrap(), message);
+    }
+
+    #[test]
+    fn get_report_sized_round_trips() {
+        let message = Message::new_get_report_sized(ReportType::Feature, 3, 0x0102);
+        let bytes = message.as_bytes();
+        assert_eq!(&*bytes, &[0x4B, 3, 0x02, 0x01]);
+        assert_eq!(Message::read_from(&bytes, true).unwrap(), message);
+    }
+
+    #[test]
+    fn get_report_sized_rejects_truncated_payload() {
+        let error = Message::read_from(&[0x4B, 3, 0x02], true).unwrap_err();
+        assert_eq!(error.kind(), ParseErrorKind::Invalid);
+        assert_eq!(error.field(), Field::ReportType);
+    }
+}
+
+#[cfg(test)]
+mod parse_error_tests {
+    use super::*;
+
+    #[test]
+    fn valid_handshake_parses() {
+        let message = Message::read_from(&[0x00], false).unwrap();
+        assert_eq!(message, Message::Handshake(handshake::SUCCESSFUL));
+    }
+
+    #[test]
+    fn reserved_message_type_is_rejected() {
+        let error = Message::read_from(&[0x20], false).unwrap_err();
+        assert_eq!(error.kind(), ParseErrorKind::Reserved);
+        assert_eq!(error.field(), Field::MessageType);
+        assert_eq!(error.value(), 0x2);
+    }
+
+    #[test]
+    fn deprecated_message_type_is_rejected() {
+        let error = Message::read_from(&[0xC0], false).unwrap_err();
+        assert_eq!(error.kind(), ParseErrorKind::Deprecated);
+        assert_eq!(error.field(), Field::MessageType);
+        assert_eq!(error.value(), 0xC);
+    }
+
+    #[test]
+    fn reserved_handshake_parameter_is_rejected() {
+        let error = Message::read_from(&[0x05], false).unwrap_err();
+        assert_eq!(error.kind(), ParseErrorKind::Reserved);
+        assert_eq!(error.field(), Field::HandshakeParameter);
+        assert_eq!(error.value(), 0x5);
+    }
+
+    #[test]
+    fn reserved_hid_control_parameter_is_rejected() {
+        let error = Message::read_from(&[0x16], false).unwrap_err();
+        assert_eq!(error.kind(), ParseErrorKind::Reserved);
+        assert_eq!(error.field(), Field::HidControlParameter);
+        assert_eq!(error.value(), 0x6);
+    }
+
+    #[test]
+    fn invalid_protocol_mode_is_rejected() {
+        let error = Message::read_from(&[0x72], false).unwrap_err();
+        assert_eq!(error.kind(), ParseErrorKind::Invalid);
+        assert_eq!(error.field(), Field::ProtocolMode);
+        assert_eq!(error.value(), 0x2);
+    }
+
+    #[test]
+    fn empty_input_is_rejected() {
+        let error = Message::read_from(&[], false).unwrap_err();
+        assert_eq!(error.field(), Field::MessageType);
+    }
 }